@@ -0,0 +1,418 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::structs::{CaptureGroup, RegexAndDFA, State, DFA};
+
+#[derive(Debug)]
+pub struct DfaBuildError(pub String);
+
+impl std::fmt::Display for DfaBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to build DFA: {}", self.0)
+    }
+}
+
+impl std::error::Error for DfaBuildError {}
+
+enum Ast {
+    Literal(u8),
+    Any,
+    Class(Vec<u8>),
+    Concat(Vec<Ast>),
+    Alt(Vec<Ast>),
+    Star(Box<Ast>),
+    Plus(Box<Ast>),
+    Opt(Box<Ast>),
+    Group(Option<String>, Box<Ast>),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(pattern: &str) -> Self {
+        Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn parse_alt(&mut self) -> Result<Ast, DfaBuildError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.bump();
+            branches.push(self.parse_concat()?);
+        }
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(Ast::Alt(branches))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Ast, DfaBuildError> {
+        let mut parts = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            parts.push(self.parse_repeat()?);
+        }
+        Ok(Ast::Concat(parts))
+    }
+
+    fn parse_repeat(&mut self) -> Result<Ast, DfaBuildError> {
+        let atom = self.parse_atom()?;
+        match self.peek() {
+            Some('*') => {
+                self.bump();
+                Ok(Ast::Star(Box::new(atom)))
+            }
+            Some('+') => {
+                self.bump();
+                Ok(Ast::Plus(Box::new(atom)))
+            }
+            Some('?') => {
+                self.bump();
+                Ok(Ast::Opt(Box::new(atom)))
+            }
+            _ => Ok(atom),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Ast, DfaBuildError> {
+        match self.bump() {
+            Some('(') => {
+                let name = if self.peek() == Some('?') {
+                    self.bump();
+                    if self.bump() != Some('P') || self.bump() != Some('<') {
+                        return Err(DfaBuildError("expected (?P<name>...) group syntax".into()));
+                    }
+                    let mut name = String::new();
+                    while let Some(c) = self.peek() {
+                        if c == '>' {
+                            break;
+                        }
+                        name.push(c);
+                        self.bump();
+                    }
+                    if self.bump() != Some('>') {
+                        return Err(DfaBuildError("unterminated group name".into()));
+                    }
+                    Some(name)
+                } else {
+                    None
+                };
+                let inner = self.parse_alt()?;
+                if self.bump() != Some(')') {
+                    return Err(DfaBuildError("unterminated group".into()));
+                }
+                Ok(Ast::Group(name, Box::new(inner)))
+            }
+            Some('[') => self.parse_class(),
+            Some('.') => Ok(Ast::Any),
+            Some('\\') => {
+                let escaped = self
+                    .bump()
+                    .ok_or_else(|| DfaBuildError("dangling escape".into()))?;
+                Ok(match escaped {
+                    'd' => Ast::Class((b'0'..=b'9').collect()),
+                    'w' => Ast::Class(
+                        (b'a'..=b'z')
+                            .chain(b'A'..=b'Z')
+                            .chain(b'0'..=b'9')
+                            .chain(std::iter::once(b'_'))
+                            .collect(),
+                    ),
+                    's' => Ast::Class(vec![b' ', b'\t', b'\n', b'\r']),
+                    other => Ast::Literal(other as u8),
+                })
+            }
+            Some(c) => Ok(Ast::Literal(c as u8)),
+            None => Err(DfaBuildError("unexpected end of pattern".into())),
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Ast, DfaBuildError> {
+        let negate = self.peek() == Some('^');
+        if negate {
+            self.bump();
+        }
+        let mut set = HashSet::new();
+        while let Some(c) = self.peek() {
+            if c == ']' {
+                break;
+            }
+            self.bump();
+            let lo = if c == '\\' {
+                self.bump()
+                    .ok_or_else(|| DfaBuildError("dangling escape in class".into()))? as u8
+            } else {
+                c as u8
+            };
+            if self.peek() == Some('-') {
+                let save = self.pos;
+                self.bump();
+                match self.peek() {
+                    Some(hi_c) if hi_c != ']' => {
+                        self.bump();
+                        for b in lo..=(hi_c as u8) {
+                            set.insert(b);
+                        }
+                        continue;
+                    }
+                    _ => self.pos = save,
+                }
+            }
+            set.insert(lo);
+        }
+        if self.bump() != Some(']') {
+            return Err(DfaBuildError("unterminated character class".into()));
+        }
+        if negate {
+            set = (0u8..=255).filter(|b| !set.contains(b)).collect();
+        }
+        Ok(Ast::Class(set.into_iter().collect()))
+    }
+}
+
+// An NFA built via Thompson's construction. state_groups records which
+// capture groups were open when a consuming transition's target was created.
+struct Nfa {
+    trans: Vec<Vec<(Option<Vec<u8>>, usize)>>,
+    state_groups: HashMap<usize, HashSet<usize>>,
+}
+
+impl Nfa {
+    fn new_state(&mut self) -> usize {
+        self.trans.push(vec![]);
+        self.trans.len() - 1
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, bytes: Option<Vec<u8>>) {
+        self.trans[from].push((bytes, to));
+    }
+}
+
+struct Builder {
+    nfa: Nfa,
+    group_names: Vec<String>,
+    open_groups: Vec<usize>,
+}
+
+impl Builder {
+    fn build(&mut self, ast: &Ast) -> (usize, usize) {
+        match ast {
+            Ast::Literal(b) => self.build_class(&[*b]),
+            Ast::Any => self.build_class(&(0u8..=255).collect::<Vec<u8>>()),
+            Ast::Class(set) => self.build_class(set),
+            Ast::Concat(parts) => {
+                if parts.is_empty() {
+                    let s = self.nfa.new_state();
+                    return (s, s);
+                }
+                let mut iter = parts.iter();
+                let (start, mut end) = self.build(iter.next().unwrap());
+                for part in iter {
+                    let (s2, e2) = self.build(part);
+                    self.nfa.add_edge(end, s2, None);
+                    end = e2;
+                }
+                (start, end)
+            }
+            Ast::Alt(branches) => {
+                let start = self.nfa.new_state();
+                let end = self.nfa.new_state();
+                for branch in branches {
+                    let (s, e) = self.build(branch);
+                    self.nfa.add_edge(start, s, None);
+                    self.nfa.add_edge(e, end, None);
+                }
+                (start, end)
+            }
+            Ast::Star(inner) => {
+                let start = self.nfa.new_state();
+                let end = self.nfa.new_state();
+                let (s, e) = self.build(inner);
+                self.nfa.add_edge(start, s, None);
+                self.nfa.add_edge(e, s, None);
+                self.nfa.add_edge(start, end, None);
+                self.nfa.add_edge(e, end, None);
+                (start, end)
+            }
+            Ast::Plus(inner) => {
+                let (s, e) = self.build(inner);
+                let end = self.nfa.new_state();
+                self.nfa.add_edge(e, s, None);
+                self.nfa.add_edge(e, end, None);
+                (s, end)
+            }
+            Ast::Opt(inner) => {
+                let start = self.nfa.new_state();
+                let end = self.nfa.new_state();
+                let (s, e) = self.build(inner);
+                self.nfa.add_edge(start, s, None);
+                self.nfa.add_edge(e, end, None);
+                self.nfa.add_edge(start, end, None);
+                (start, end)
+            }
+            Ast::Group(name, inner) => {
+                let group_id = self.group_names.len();
+                self.group_names
+                    .push(name.clone().unwrap_or_else(|| format!("group{group_id}")));
+                self.open_groups.push(group_id);
+                let (s, e) = self.build(inner);
+                self.open_groups.pop();
+                (s, e)
+            }
+        }
+    }
+
+    fn build_class(&mut self, bytes: &[u8]) -> (usize, usize) {
+        let start = self.nfa.new_state();
+        let end = self.nfa.new_state();
+        self.nfa.add_edge(start, end, Some(bytes.to_vec()));
+        if !self.open_groups.is_empty() {
+            self.nfa
+                .state_groups
+                .entry(end)
+                .or_default()
+                .extend(self.open_groups.iter().copied());
+        }
+        (start, end)
+    }
+}
+
+// Parses pattern into an AST, builds an NFA via Thompson's construction, then
+// determinizes it via subset construction, tagging each DFA state with the
+// capture groups any NFA state in its subset was reached inside of.
+pub fn build(pattern: &str) -> Result<RegexAndDFA, DfaBuildError> {
+    let mut core = pattern;
+    let has_end_anchor = core.ends_with('$') && !core.ends_with("\\$");
+    if has_end_anchor {
+        core = &core[..core.len() - 1];
+    }
+    let core = core.strip_prefix('^').unwrap_or(core);
+
+    let mut parser = Parser::new(core);
+    let ast = parser.parse_alt()?;
+    if parser.pos != parser.chars.len() {
+        return Err(DfaBuildError(format!(
+            "unexpected trailing input at offset {}",
+            parser.pos
+        )));
+    }
+
+    let mut builder = Builder {
+        nfa: Nfa {
+            trans: vec![],
+            state_groups: HashMap::new(),
+        },
+        group_names: vec![],
+        open_groups: vec![],
+    };
+    let (start, accept) = builder.build(&ast);
+    let nfa = builder.nfa;
+    let group_names = builder.group_names;
+
+    let closure = |states: &BTreeSet<usize>| -> BTreeSet<usize> {
+        let mut stack: Vec<usize> = states.iter().copied().collect();
+        let mut seen: BTreeSet<usize> = states.clone();
+        while let Some(s) = stack.pop() {
+            for (bytes, to) in &nfa.trans[s] {
+                if bytes.is_none() && seen.insert(*to) {
+                    stack.push(*to);
+                }
+            }
+        }
+        seen
+    };
+
+    let start_set = closure(&BTreeSet::from([start]));
+    let mut dfa_states: Vec<BTreeSet<usize>> = vec![start_set.clone()];
+    let mut dfa_index: HashMap<BTreeSet<usize>, usize> = HashMap::from([(start_set, 0)]);
+    let mut dfa_trans: Vec<HashMap<usize, Vec<u8>>> = vec![HashMap::new()];
+    let mut queue = vec![0usize];
+
+    while let Some(idx) = queue.pop() {
+        let current = dfa_states[idx].clone();
+        let mut per_byte_targets: HashMap<u8, BTreeSet<usize>> = HashMap::new();
+        for &s in &current {
+            for (bytes, to) in &nfa.trans[s] {
+                if let Some(bytes) = bytes {
+                    for &b in bytes {
+                        per_byte_targets.entry(b).or_default().insert(*to);
+                    }
+                }
+            }
+        }
+
+        let mut target_to_bytes: HashMap<BTreeSet<usize>, Vec<u8>> = HashMap::new();
+        for (b, targets) in per_byte_targets {
+            target_to_bytes.entry(closure(&targets)).or_default().push(b);
+        }
+
+        for (target, bytes) in target_to_bytes {
+            let next_idx = *dfa_index.entry(target.clone()).or_insert_with(|| {
+                dfa_states.push(target);
+                dfa_trans.push(HashMap::new());
+                let i = dfa_states.len() - 1;
+                queue.push(i);
+                i
+            });
+            dfa_trans[idx].insert(next_idx, bytes);
+        }
+    }
+
+    let states = dfa_states
+        .iter()
+        .enumerate()
+        .map(|(id, subset)| State {
+            state_id: id,
+            state_type: if subset.contains(&accept) {
+                "accept"
+            } else {
+                "normal"
+            }
+            .to_owned(),
+            transitions: dfa_trans[id].clone(),
+        })
+        .collect();
+
+    let mut capture_groups: Vec<CaptureGroup> = group_names
+        .into_iter()
+        .map(|name| CaptureGroup {
+            name,
+            state_ids: HashSet::new(),
+        })
+        .collect();
+    for (dfa_id, subset) in dfa_states.iter().enumerate() {
+        for nfa_state in subset {
+            if let Some(groups) = nfa.state_groups.get(nfa_state) {
+                for &group_id in groups {
+                    capture_groups[group_id].state_ids.insert(dfa_id);
+                }
+            }
+        }
+    }
+
+    Ok(RegexAndDFA {
+        regex_pattern: pattern.to_owned(),
+        has_end_anchor,
+        dfa: DFA { states },
+        capture_groups,
+    })
+}
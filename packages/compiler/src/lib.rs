@@ -0,0 +1,3 @@
+pub mod dfa;
+pub mod noir;
+pub mod structs;
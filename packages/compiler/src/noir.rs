@@ -1,4 +1,9 @@
-use std::{cmp::max, fs::File, io::Write, path::Path};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::Write,
+    path::Path,
+};
 
 use itertools::Itertools;
 
@@ -14,59 +19,304 @@ pub fn gen_noir_fn(regex_and_dfa: &RegexAndDFA, path: &Path) -> Result<(), std::
     Ok(())
 }
 
-fn to_noir_fn(regex_and_dfa: &RegexAndDFA) -> String {
-    let accept_state_ids = {
-        let accept_states = regex_and_dfa
-            .dfa
-            .states
-            .iter()
-            .filter(|s| s.state_type == ACCEPT_STATE_ID)
-            .map(|s| s.state_id)
-            .collect_vec();
-        assert!(accept_states.len() > 0, "no accept states");
-        accept_states
-    };
+// curr_state + byte -> next_state rows and accept states, renumbered after minimization.
+struct MinimizedDfa {
+    rows: Vec<(usize, u8, usize)>,
+    accept_state_ids: Vec<usize>,
+    highest_state: usize,
+    // original dfa.states id -> post-minimization id
+    state_id_map: HashMap<usize, usize>,
+}
 
-    const BYTE_SIZE: u32 = 256; // u8 size
-    let mut lookup_table_body = String::new();
+// Collapses equivalent states with Hopcroft's algorithm before table codegen.
+fn minimize_dfa(regex_and_dfa: &RegexAndDFA) -> MinimizedDfa {
+    let states = &regex_and_dfa.dfa.states;
+    let num_states = states.len();
+    let dead_state = num_states;
 
-    // curr_state + char_code -> next_state
-    let mut rows: Vec<(usize, u8, usize)> = vec![];
+    // trans[state][byte] = next_state, total over every byte. Every row
+    // (including the dead state's) starts as all-dead_state already, so the
+    // dead state's row needs no further initialization.
+    let mut trans: Vec<[usize; 256]> = vec![[dead_state; 256]; num_states + 1];
+    for state in states.iter() {
+        for (&next_id, bytes) in &state.transitions {
+            for &byte in bytes {
+                trans[state.state_id][byte as usize] = next_id;
+            }
+        }
+    }
+
+    let accept: HashSet<usize> = states
+        .iter()
+        .filter(|s| s.state_type == ACCEPT_STATE_ID)
+        .map(|s| s.state_id)
+        .collect();
+    let non_accept: HashSet<usize> = (0..=dead_state).filter(|s| !accept.contains(s)).collect();
 
+    // Blocks of states that are (so far) indistinguishable from one another.
+    let mut partitions: Vec<HashSet<usize>> = vec![accept.clone(), non_accept.clone()];
+    let mut worklist: VecDeque<usize> = VecDeque::new();
+    worklist.push_back(if accept.len() <= non_accept.len() { 0 } else { 1 });
+
+    while let Some(a_idx) = worklist.pop_front() {
+        let a_block = partitions[a_idx].clone();
+        // `byte` only ever indexes per-state `trans[s]` rows (s itself comes
+        // from a separate filter below), so there's no single slice this
+        // loop could walk instead.
+        #[allow(clippy::needless_range_loop)]
+        for byte in 0..256usize {
+            // X: every state whose transition on `byte` lands inside block A.
+            let x: HashSet<usize> = (0..=dead_state)
+                .filter(|&s| a_block.contains(&trans[s][byte]))
+                .collect();
+            if x.is_empty() {
+                continue;
+            }
+            for y_idx in 0..partitions.len() {
+                let y = &partitions[y_idx];
+                let intersect: HashSet<usize> = y.intersection(&x).cloned().collect();
+                if intersect.is_empty() || intersect.len() == y.len() {
+                    continue;
+                }
+                let diff: HashSet<usize> = y.difference(&x).cloned().collect();
+                partitions[y_idx] = intersect.clone();
+                partitions.push(diff.clone());
+                let new_idx = partitions.len() - 1;
+
+                if worklist.contains(&y_idx) {
+                    worklist.push_back(new_idx);
+                } else if intersect.len() <= diff.len() {
+                    worklist.push_back(y_idx);
+                } else {
+                    worklist.push_back(new_idx);
+                }
+            }
+        }
+    }
+
+    // Assign each surviving block a new, compact state id. The block holding
+    // state 0 (the DFA start state) keeps id 0 so downstream code that
+    // assumes the walk starts at state 0 keeps working unchanged.
+    let start_block = partitions
+        .iter()
+        .position(|b| b.contains(&0))
+        .expect("start state must belong to some block");
+    let mut block_order = (0..partitions.len()).collect_vec();
+    block_order.swap(0, start_block);
+
+    let mut new_id_of_state: HashMap<usize, usize> = HashMap::new();
+    for (new_id, &block_idx) in block_order.iter().enumerate() {
+        for &state in &partitions[block_idx] {
+            new_id_of_state.insert(state, new_id);
+        }
+    }
 
-    let mut highest_state = 0;
-    for state in regex_and_dfa.dfa.states.iter() {
-        for (&tran_next_state_id, tran) in &state.transitions {
-            for &char_code in tran {
-                rows.push((state.state_id, char_code, tran_next_state_id));
+    let mut rows: Vec<(usize, u8, usize)> = vec![];
+    let mut seen: HashMap<(usize, u8), usize> = HashMap::new();
+    for state in states.iter() {
+        let curr = new_id_of_state[&state.state_id];
+        for (&next_id, bytes) in &state.transitions {
+            let next = new_id_of_state[&next_id];
+            for &byte in bytes {
+                match seen.insert((curr, byte), next) {
+                    None => rows.push((curr, byte, next)),
+                    // Two pre-minimization states landed in the same block
+                    // but don't actually agree on where (curr, byte) goes,
+                    // i.e. minimization merged non-equivalent states. This
+                    // must not silently miscompile a release build, so it's
+                    // a real assert, not a debug-only one.
+                    Some(prev) => assert_eq!(
+                        prev, next,
+                        "minimize_dfa merged non-equivalent states: ({curr}, {byte}) -> {prev} vs {next}"
+                    ),
+                }
             }
         }
-        highest_state = max(state.state_id, highest_state);
+    }
+    // The start-marker step (byte 255 from state 0) stays in state 0 unless
+    // the DFA has its own transition on that byte, instead of falling into
+    // the invalid state and rejecting every input.
+    if !seen.contains_key(&(0, 255)) {
+        rows.push((0, 255, 0));
     }
 
-    for (curr_state_id, char_code, next_state_id) in rows {
-        lookup_table_body +=
-            &format!("table[{curr_state_id} * {BYTE_SIZE} + {char_code}] = {next_state_id};\n",);
+    let accept_state_ids = accept
+        .iter()
+        .map(|&s| new_id_of_state[&s])
+        .unique()
+        .collect_vec();
+    assert!(accept_state_ids.len() > 0, "no accept states");
+
+    // The dead state's block never receives an explicit row (nothing ever
+    // transitions into it on purpose), so it doesn't inflate `highest_state`
+    // as long as no live state merged past it; fall back to the largest id
+    // any row actually references.
+    let highest_state = rows
+        .iter()
+        .flat_map(|&(curr, _, next)| [curr, next])
+        .max()
+        .unwrap_or(0);
+
+    MinimizedDfa {
+        rows,
+        accept_state_ids,
+        highest_state,
+        state_id_map: new_id_of_state,
+    }
+}
+
+// Bytes that drive every state to the same next state collapse into one class.
+struct ByteClasses {
+    class_of_byte: [usize; 256],
+    num_classes: usize,
+}
+
+// Computes byte equivalence classes over the transition grid implied by `rows`.
+fn byte_equivalence_classes(rows: &[(usize, u8, usize)], num_states: usize) -> ByteClasses {
+    // usize::MAX marks "no transition", so it never shares a class with a
+    // real transition back to state 0.
+    let mut grid = vec![[usize::MAX; 256]; num_states];
+    for &(curr, byte, next) in rows {
+        grid[curr][byte as usize] = next;
+    }
+
+    let mut class_of_byte = [0usize; 256];
+    let mut signatures: Vec<Vec<usize>> = vec![];
+    for byte in 0..256usize {
+        let column = (0..num_states).map(|s| grid[s][byte]).collect_vec();
+        let class = match signatures.iter().position(|sig| sig == &column) {
+            Some(idx) => idx,
+            None => {
+                signatures.push(column);
+                signatures.len() - 1
+            }
+        };
+        class_of_byte[byte] = class;
+    }
+
+    ByteClasses {
+        class_of_byte,
+        num_classes: signatures.len(),
+    }
+}
+
+// Emits one regex_match_capture_<name> function per capture group, returning
+// the input masked down to the bytes consumed inside that group plus the count.
+fn to_noir_capture_fns(
+    regex_and_dfa: &RegexAndDFA,
+    state_id_map: &HashMap<usize, usize>,
+    num_classes: usize,
+) -> String {
+    regex_and_dfa
+        .capture_groups
+        .iter()
+        .map(|group| {
+            let inside_state_ids = group
+                .state_ids
+                .iter()
+                .map(|id| state_id_map[id])
+                .unique()
+                .collect_vec();
+            let in_group_condition = if inside_state_ids.is_empty() {
+                "false".to_owned()
+            } else {
+                inside_state_ids
+                    .iter()
+                    .map(|id| format!("(s == {id})"))
+                    .collect_vec()
+                    .join(" | ")
+            };
+            format!(
+                r#"
+pub fn regex_match_capture_{name}<let N: u32>(input: [u8; N]) -> ([u8; N], Field) {{
+    // regex: {regex_pattern}
+    // capture group: {name}
+    let mut s = 0;
+    let mut out = [0 as u8; N];
+    let mut len: Field = 0;
+    s = table[s * {num_classes} + byte_class[255 as Field]];
+    for i in 0..input.len() {{
+        let c = byte_class[input[i] as Field];
+        s = table[s * {num_classes} + c];
+        let in_group = {in_group_condition};
+        out[i] = if in_group {{ input[i] }} else {{ 0 }};
+        len = if in_group {{ len + 1 }} else {{ len }};
+    }}
+    (out, len)
+}}
+"#,
+                name = group.name,
+                regex_pattern = regex_and_dfa.regex_pattern,
+            )
+        })
+        .collect::<String>()
+}
+
+// The per-pattern pieces to_noir_fn and to_noir_fn_set both need.
+struct DfaTables {
+    lookup_table: String,
+    byte_class_table: String,
+    num_classes: usize,
+    accept_state_ids: Vec<usize>,
+    state_id_map: HashMap<usize, usize>,
+}
+
+// Builds the minimized, class-compressed table/byte-class comptime functions
+// for one RegexAndDFA; `suffix` keeps names unique when several share a file.
+fn build_dfa_tables(regex_and_dfa: &RegexAndDFA, suffix: &str) -> DfaTables {
+    let minimized = minimize_dfa(regex_and_dfa);
+    let accept_state_ids = minimized.accept_state_ids;
+    let rows = minimized.rows;
+    let highest_state = minimized.highest_state;
+    let state_id_map = minimized.state_id_map;
+    let num_states = highest_state + 1;
+
+    let byte_classes = byte_equivalence_classes(&rows, num_states);
+    let num_classes = byte_classes.num_classes;
+
+    let mut lookup_table_body = String::new();
+    let mut emitted: HashSet<(usize, usize)> = HashSet::new();
+    for &(curr_state_id, char_code, next_state_id) in &rows {
+        let class = byte_classes.class_of_byte[char_code as usize];
+        if emitted.insert((curr_state_id, class)) {
+            lookup_table_body += &format!(
+                "table[{curr_state_id} * {num_classes} + {class}] = {next_state_id};\n",
+            );
+        }
     }
 
     lookup_table_body = indent(&lookup_table_body);
-    let table_size = BYTE_SIZE as usize * regex_and_dfa.dfa.states.len();
+    let table_size = num_classes * num_states;
 
-    // If the regex ends with `$`, use this invalid state to invalidate
-    // any transitions after `$`
+    // Every (state, byte-class) pair with no live transition routes here,
+    // so "no explicit transition" can never alias with "loop back to state
+    // 0" (which would let an unmatched byte detour back to an accepting
+    // path instead of rejecting the input).
     let invalid_state = highest_state + 1;
-    
+
+    // Default every cell to the invalid state before any live transition is
+    // written, so rows below only need to cover the transitions that exist.
+    let default_fill = indent(&format!(
+        r#"
+for s in 0..{num_states} {{
+    for i in 0..{num_classes} {{
+        table[s * {num_classes} + i] = {invalid_state};
+    }}
+}}
+        "#
+    ));
+
     let mut end_anchor_logic = String::new();
     // If regex_and_dfa.has_end_anchor tells us where the regex ends with `$`
     if regex_and_dfa.has_end_anchor {
       // If so, add transitions from each accept state to invalid state
       // these can be overwritten by valid transitions from accept state further on
       for acc_state in accept_state_ids.clone() {
-        end_anchor_logic += 
+        end_anchor_logic +=
         &format!(
           r#"
-for i in 0..{BYTE_SIZE} {{
-    table[{acc_state} * {BYTE_SIZE} + i] = {invalid_state};
+for i in 0..{num_classes} {{
+    table[{acc_state} * {num_classes} + i] = {invalid_state};
 }}
             "#
         );
@@ -76,8 +326,9 @@ for i in 0..{BYTE_SIZE} {{
 
     let lookup_table = format!(
         r#"
-comptime fn make_lookup_table() -> [Field; {table_size}] {{
+comptime fn make_lookup_table{suffix}() -> [Field; {table_size}] {{
     let mut table = [0; {table_size}];
+    {default_fill}
     {end_anchor_logic}
 {lookup_table_body}
     table
@@ -85,6 +336,41 @@ comptime fn make_lookup_table() -> [Field; {table_size}] {{
       "#
     );
 
+    let byte_class_body = indent(
+        &byte_classes
+            .class_of_byte
+            .iter()
+            .enumerate()
+            .filter(|&(_, &class)| class != 0)
+            .map(|(byte, class)| format!("classes[{byte}] = {class};\n"))
+            .collect::<String>(),
+    );
+    let byte_class_table = format!(
+        r#"
+comptime fn make_byte_class{suffix}() -> [Field; 256] {{
+    let mut classes = [0; 256];
+{byte_class_body}
+    classes
+}}
+      "#
+    );
+
+    DfaTables {
+        lookup_table,
+        byte_class_table,
+        num_classes,
+        accept_state_ids,
+        state_id_map,
+    }
+}
+
+fn to_noir_fn(regex_and_dfa: &RegexAndDFA) -> String {
+    let tables = build_dfa_tables(regex_and_dfa, "");
+    let num_classes = tables.num_classes;
+    let accept_state_ids = tables.accept_state_ids;
+    let lookup_table = tables.lookup_table;
+    let byte_class_table = tables.byte_class_table;
+
     let final_states_condition_body = accept_state_ids
         .iter()
         .map(|id| format!("(s == {id})"))
@@ -93,22 +379,106 @@ comptime fn make_lookup_table() -> [Field; {table_size}] {{
     let fn_body = format!(
         r#"
 global table = comptime {{ make_lookup_table() }};
+global byte_class = comptime {{ make_byte_class() }};
 pub fn regex_match<let N: u32>(input: [u8; N]) {{
     // regex: {regex_pattern}
     let mut s = 0;
-    s = table[s * 256 + 255 as Field];
+    s = table[s * {num_classes} + byte_class[255 as Field]];
     for i in 0..input.len() {{
-        s = table[s * {BYTE_SIZE} + input[i] as Field];
+        let c = byte_class[input[i] as Field];
+        s = table[s * {num_classes} + c];
     }}
     assert({final_states_condition_body}, f"no match: {{s}}");
 }}
     "#,
         regex_pattern = regex_and_dfa.regex_pattern,
     );
+    let capture_fns = to_noir_capture_fns(regex_and_dfa, &tables.state_id_map, num_classes);
     format!(
         r#"
         {fn_body}
         {lookup_table}
+        {byte_class_table}
+        {capture_fns}
+    "#
+    )
+    .trim()
+    .to_owned()
+}
+
+// Like gen_noir_fn, but compiles patterns into one regex_match_set function
+// that walks the shared input once and returns a bitmask of which matched.
+pub fn gen_noir_fn_set(patterns: &[RegexAndDFA], path: &Path) -> Result<(), std::io::Error> {
+    let noir_fn = to_noir_fn_set(patterns);
+    let mut file = File::create(path)?;
+    file.write_all(noir_fn.as_bytes())?;
+    file.flush()?;
+    Ok(())
+}
+
+fn to_noir_fn_set(patterns: &[RegexAndDFA]) -> String {
+    assert!(!patterns.is_empty(), "no patterns given");
+    let num_patterns = patterns.len();
+
+    let mut tables_code = String::new();
+    let mut globals_code = String::new();
+    let mut state_decls = String::new();
+    let mut start_step_body = String::new();
+    let mut walk_step_body = String::new();
+    let mut bitmask_body = String::new();
+
+    for (i, pattern) in patterns.iter().enumerate() {
+        let suffix = format!("_{i}");
+        let tables = build_dfa_tables(pattern, &suffix);
+        let num_classes = tables.num_classes;
+
+        tables_code += &tables.lookup_table;
+        tables_code += &tables.byte_class_table;
+        globals_code += &format!(
+            "global table{suffix} = comptime {{ make_lookup_table{suffix}() }};\nglobal byte_class{suffix} = comptime {{ make_byte_class{suffix}() }};\n"
+        );
+
+        state_decls += &format!("let mut s{i} = 0;\n");
+        start_step_body += &format!(
+            "s{i} = table{suffix}[s{i} * {num_classes} + byte_class{suffix}[255 as Field]];\n",
+        );
+        walk_step_body += &format!(
+            "let c{i} = byte_class{suffix}[input[i] as Field];\ns{i} = table{suffix}[s{i} * {num_classes} + c{i}];\n",
+        );
+
+        let accept_condition = tables
+            .accept_state_ids
+            .iter()
+            .map(|id| format!("(s{i} == {id})"))
+            .collect_vec()
+            .join(" | ");
+        bitmask_body += &format!("matched[{i}] = {accept_condition};\n");
+    }
+
+    let state_decls = indent(&state_decls);
+    let start_step_body = indent(&start_step_body);
+    let walk_step_body = indent(&indent(&walk_step_body));
+    let bitmask_body = indent(&bitmask_body);
+
+    let fn_body = format!(
+        r#"
+{globals_code}pub fn regex_match_set<let N: u32>(input: [u8; N]) -> [bool; {num_patterns}] {{
+{state_decls}
+{start_step_body}
+    for i in 0..input.len() {{
+{walk_step_body}
+    }}
+    let mut matched = [false; {num_patterns}];
+{bitmask_body}
+    matched
+}}
+    "#,
+    );
+
+    format!(
+        r#"
+        {fn_body}
+        {tables_code}
     "#
     )
     .trim()
@@ -127,3 +497,225 @@ fn indent(s: &str) -> String {
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+#[cfg(test)]
+mod differential_tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    // Fixed so a real regression always fails, instead of getting a
+    // coin-flip chance of landing on an input that doesn't trigger it.
+    const FUZZ_SEED: u64 = 0xc0ffee_d0d0;
+
+    // A replay of one pattern's walk over `input` using to_noir_fn's table
+    // logic; trace[0] is the state after the start marker, trace[i + 1]
+    // after consuming input[i].
+    struct Walk {
+        trace: Vec<usize>,
+        accept: HashSet<usize>,
+        state_id_map: HashMap<usize, usize>,
+    }
+
+    fn walk(regex_and_dfa: &RegexAndDFA, input: &[u8]) -> Walk {
+        let minimized = minimize_dfa(regex_and_dfa);
+        let num_states = minimized.highest_state + 1;
+        let byte_classes = byte_equivalence_classes(&minimized.rows, num_states);
+        let invalid_state = minimized.highest_state + 1;
+        let accept: HashSet<usize> = minimized.accept_state_ids.iter().copied().collect();
+
+        let mut dense: HashMap<(usize, usize), usize> = HashMap::new();
+        for &(curr, byte, next) in &minimized.rows {
+            dense.insert((curr, byte_classes.class_of_byte[byte as usize]), next);
+        }
+        // Mirrors `build_dfa_tables`'s default fill: any (state, class) with
+        // no live transition routes to the invalid state, never back to 0.
+        let step = |s: usize, byte: usize| -> usize {
+            let class = byte_classes.class_of_byte[byte];
+            dense.get(&(s, class)).copied().unwrap_or(invalid_state)
+        };
+
+        let mut s = step(0, 255);
+        let mut trace = vec![s];
+        for &b in input {
+            s = step(s, b as usize);
+            trace.push(s);
+        }
+
+        Walk {
+            trace,
+            accept,
+            state_id_map: minimized.state_id_map,
+        }
+    }
+
+    // What regex_match asserts.
+    fn simulate(regex_and_dfa: &RegexAndDFA, input: &[u8]) -> bool {
+        let walk = walk(regex_and_dfa, input);
+        walk.accept.contains(walk.trace.last().unwrap())
+    }
+
+    // What regex_match_capture_<name> returns.
+    fn simulate_capture(regex_and_dfa: &RegexAndDFA, group_name: &str, input: &[u8]) -> (Vec<u8>, usize) {
+        let walk = walk(regex_and_dfa, input);
+        let group = regex_and_dfa
+            .capture_groups
+            .iter()
+            .find(|g| g.name == group_name)
+            .unwrap_or_else(|| panic!("no capture group named {group_name:?}"));
+        let inside: HashSet<usize> = group
+            .state_ids
+            .iter()
+            .map(|id| walk.state_id_map[id])
+            .collect();
+
+        let mut out = vec![0u8; input.len()];
+        let mut len = 0;
+        for (i, &b) in input.iter().enumerate() {
+            if inside.contains(&walk.trace[i + 1]) {
+                out[i] = b;
+                len += 1;
+            }
+        }
+        (out, len)
+    }
+
+    // What regex_match_set returns: each pattern's table is independent.
+    fn simulate_set(patterns: &[RegexAndDFA], input: &[u8]) -> Vec<bool> {
+        patterns.iter().map(|p| simulate(p, input)).collect()
+    }
+
+    #[derive(serde::Deserialize)]
+    struct VectorFile {
+        vector: Vec<Vector>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Vector {
+        pattern: String,
+        #[serde(default)]
+        input: Option<String>,
+        #[serde(default)]
+        input_bytes: Option<Vec<u8>>,
+        expect_match: bool,
+    }
+
+    impl Vector {
+        fn input_bytes(&self) -> Vec<u8> {
+            self.input_bytes
+                .clone()
+                .unwrap_or_else(|| self.input.clone().unwrap_or_default().into_bytes())
+        }
+    }
+
+    fn load_vectors() -> Vec<Vector> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/differential_vectors.toml");
+        let raw = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {e}", path.display()));
+        toml::from_str::<VectorFile>(&raw)
+            .expect("failed to parse differential test vectors")
+            .vector
+    }
+
+    #[test]
+    fn generated_circuit_matches_regex_crate() {
+        let mut rng = StdRng::seed_from_u64(FUZZ_SEED);
+        for v in load_vectors() {
+            let regex_and_dfa =
+                RegexAndDFA::new(&v.pattern).expect("failed to build DFA from pattern");
+            // The circuit walks the whole input from state 0 and only
+            // accepts if the final state is an accept state, i.e. it's a
+            // full-string match, not `regex`'s default unanchored search.
+            let reference = regex::Regex::new(&format!("^(?:{})$", v.pattern))
+                .expect("failed to compile reference regex");
+
+            let fixture_input = v.input_bytes();
+            assert_eq!(
+                simulate(&regex_and_dfa, &fixture_input),
+                v.expect_match,
+                "fixture mismatch for pattern {:?} on {:?}",
+                v.pattern,
+                fixture_input
+            );
+
+            let mut corpus: Vec<Vec<u8>> = vec![vec![], vec![0], vec![255], fixture_input];
+            for _ in 0..200 {
+                let len = rng.gen_range(0..12);
+                corpus.push((0..len).map(|_| rng.gen::<u8>()).collect());
+            }
+
+            for input in &corpus {
+                let text = String::from_utf8_lossy(input);
+                // Only a valid-UTF8, round-tripping input is a fair
+                // comparison against the `regex` crate's `&str` API.
+                if text.as_bytes() != input.as_slice() {
+                    continue;
+                }
+                let want = reference.is_match(&text);
+                assert_eq!(
+                    simulate(&regex_and_dfa, input),
+                    want,
+                    "circuit/regex-crate disagreement for pattern {:?} on {:?}",
+                    v.pattern,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn capture_group_masks_only_its_own_substring() {
+        let regex_and_dfa = RegexAndDFA::new(r"[a-z]+@(?P<domain>[a-z]+\.[a-z]+)")
+            .expect("failed to build DFA from pattern");
+        let input = b"user@example.com";
+        let domain_start = input.iter().position(|&b| b == b'@').unwrap() + 1;
+
+        let mut expected = vec![0u8; input.len()];
+        expected[domain_start..].copy_from_slice(&input[domain_start..]);
+
+        let (masked, len) = simulate_capture(&regex_and_dfa, "domain", input);
+        assert_eq!(masked, expected);
+        assert_eq!(len, input.len() - domain_start);
+    }
+
+    #[test]
+    fn regex_set_bitmask_matches_regex_crate_per_pattern() {
+        let pattern_strs = ["a+", r"[a-z]+@[a-z]+\.[a-z]+", "a+$"];
+        let patterns = pattern_strs
+            .iter()
+            .map(|p| RegexAndDFA::new(p).expect("failed to build DFA from pattern"))
+            .collect_vec();
+        let references = pattern_strs
+            .iter()
+            .map(|p| regex::Regex::new(&format!("^(?:{p})$")).expect("failed to compile reference regex"))
+            .collect_vec();
+
+        let mut rng = StdRng::seed_from_u64(FUZZ_SEED);
+        let mut corpus: Vec<Vec<u8>> = vec![
+            vec![],
+            b"aaa".to_vec(),
+            b"user@example.com".to_vec(),
+            b"aaab".to_vec(),
+        ];
+        for _ in 0..100 {
+            let len = rng.gen_range(0..10);
+            corpus.push((0..len).map(|_| rng.gen::<u8>()).collect());
+        }
+
+        for input in &corpus {
+            let text = String::from_utf8_lossy(input);
+            if text.as_bytes() != input.as_slice() {
+                continue;
+            }
+            let want = references.iter().map(|r| r.is_match(&text)).collect_vec();
+            assert_eq!(
+                simulate_set(&patterns, input),
+                want,
+                "bitmask mismatch on {:?}",
+                input
+            );
+        }
+    }
+}
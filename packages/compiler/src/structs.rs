@@ -0,0 +1,31 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::dfa::{self, DfaBuildError};
+
+pub struct State {
+    pub state_id: usize,
+    pub state_type: String,
+    pub transitions: HashMap<usize, Vec<u8>>,
+}
+
+pub struct DFA {
+    pub states: Vec<State>,
+}
+
+pub struct CaptureGroup {
+    pub name: String,
+    pub state_ids: HashSet<usize>,
+}
+
+pub struct RegexAndDFA {
+    pub regex_pattern: String,
+    pub has_end_anchor: bool,
+    pub dfa: DFA,
+    pub capture_groups: Vec<CaptureGroup>,
+}
+
+impl RegexAndDFA {
+    pub fn new(pattern: &str) -> Result<Self, DfaBuildError> {
+        dfa::build(pattern)
+    }
+}